@@ -43,10 +43,12 @@
 
 #![allow(unused_variables, dead_code)]
 
-use rand::{distributions::Alphanumeric, Rng};
+use rand::Rng;
 use std::collections::{BTreeMap, HashMap};
 use url::Url as Vurl;
 
+use events::Event;
+
 /// All possible errors of the [`UrlShortenerService`].
 #[derive(Debug, PartialEq)]
 pub enum ShortenerError {
@@ -92,6 +94,119 @@ pub struct Stats {
     pub redirects: u64,
 }
 
+/// Alphabet used by [`SlugConfig::unambiguous`] when minting a random
+/// [`Slug`], excluding the characters most often confused with one another
+/// (`0`/`O`, `1`/`l`).
+const UNAMBIGUOUS_ALPHABET: &[char] = &[
+    '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k',
+    'm', 'n', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', 'A', 'B', 'C', 'D', 'E', 'F',
+    'G', 'H', 'I', 'J', 'K', 'M', 'N', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
+];
+
+/// Slugs that are never minted or accepted as a custom alias, because they
+/// would shadow a route the service reserves for itself.
+const RESERVED_SLUGS: &[&str] = &["api", "health", "stats", "admin", "new", "favicon.ico"];
+
+fn is_reserved(slug: &str) -> bool {
+    RESERVED_SLUGS.contains(&slug)
+}
+
+/// Configuration for the random [`Slug`] generator used by
+/// [`UrlShortenerService`] when no custom slug is supplied.
+#[derive(Debug, Clone)]
+pub struct SlugConfig {
+    /// Number of characters in a generated slug.
+    pub length: usize,
+
+    /// Characters a generated slug may be drawn from.
+    pub alphabet: Vec<char>,
+
+    /// How many generation attempts to make in total before giving up on
+    /// collisions with [`ShortenerError::SlugAlreadyInUse`].
+    pub max_attempts: u32,
+}
+
+impl SlugConfig {
+    /// Generated slugs will contain `length` characters drawn from the full
+    /// alphanumeric alphabet.
+    pub fn new(length: usize) -> Self {
+        Self {
+            length,
+            ..Self::default()
+        }
+    }
+
+    /// Generated slugs will contain `length` characters drawn from an
+    /// unambiguous alphabet (no `0`/`O`/`1`/`l`), for human-friendly slugs.
+    pub fn unambiguous(length: usize) -> Self {
+        Self {
+            length,
+            alphabet: UNAMBIGUOUS_ALPHABET.to_vec(),
+            ..Self::default()
+        }
+    }
+
+    /// Falls back to [`SlugConfig::default`]'s alphabet/length for whichever
+    /// of the two is degenerate (an empty alphabet, or a zero length), so a
+    /// config built by hand can never make slug generation panic or loop
+    /// forever minting the same empty slug.
+    fn sanitized(mut self) -> Self {
+        if self.alphabet.is_empty() {
+            self.alphabet = Self::default().alphabet;
+        }
+        if self.length == 0 {
+            self.length = Self::default().length;
+        }
+        self
+    }
+}
+
+impl Default for SlugConfig {
+    fn default() -> Self {
+        Self {
+            length: 7,
+            alphabet: (b'0'..=b'9')
+                .chain(b'a'..=b'z')
+                .chain(b'A'..=b'Z')
+                .map(char::from)
+                .collect(),
+            max_attempts: 10,
+        }
+    }
+}
+
+/// Event Sourcing log: the append-only history the [`UrlShortenerService`]
+/// is derived from.
+pub mod events {
+    use super::{Slug, Url};
+
+    /// A single fact recorded by the [`UrlShortenerService`](super::UrlShortenerService).
+    ///
+    /// The service never mutates its projections directly; every state
+    /// change is first expressed as an `Event` and then folded into the
+    /// in-memory state by `apply`, so live handling and replay share the
+    /// exact same code path.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Event {
+        /// A new [`ShortLink`](super::ShortLink) was created.
+        ShortLinkCreated {
+            /// Slug assigned to the new short link.
+            slug: Slug,
+            /// Original URL the slug points to.
+            url: Url,
+            /// Monotonic sequence number among `ShortLinkCreated` events;
+            /// `Redirected` events do not take one.
+            at_seq: u64,
+        },
+
+        /// A short link was followed.
+        Redirected {
+            /// Slug that was redirected through.
+            slug: Slug,
+        },
+    }
+}
+
 /// Commands for CQRS.
 pub mod commands {
     use super::{ShortLink, ShortenerError, Slug, Url};
@@ -119,7 +234,7 @@ pub mod commands {
 
 /// Queries for CQRS
 pub mod queries {
-    use super::{ShortenerError, Slug, Stats};
+    use super::{ShortenerError, Slug, Stats, Url};
 
     /// Trait for query handlers.
     pub trait QueryHandler {
@@ -129,22 +244,159 @@ pub mod queries {
         /// [`ShortLink`]: super::ShortLink
         fn get_stats(&self, slug: Slug) -> Result<Stats, ShortenerError>;
     }
+
+    /// Trait for previewing and recognizing short links, without affecting
+    /// redirect counts.
+    pub trait ResolveHandler {
+        /// Returns the original [`Url`] a [`Slug`] points to, without
+        /// recording a redirect. Use [`CommandHandler::handle_redirect`] for
+        /// the following-a-link path instead.
+        ///
+        /// ## Errors
+        ///
+        /// See [`ShortenerError`].
+        ///
+        /// [`CommandHandler::handle_redirect`]: super::commands::CommandHandler::handle_redirect
+        fn resolve(&self, slug: Slug) -> Result<Url, ShortenerError>;
+
+        /// Reports whether `url` — either a bare [`Slug`] or a full short
+        /// URL ending in one — is managed by this service.
+        fn is_shortened(&self, url: &Url) -> bool;
+    }
 }
 
 /// CQRS and Event Sourcing-based service implementation
 pub struct UrlShortenerService {
-    // TODO: add needed fields
+    /// Append-only log of every [`Event`] the service has ever produced.
+    events: Vec<Event>,
+
+    /// Projection: slug -> target URL, folded from `events`.
     slugs: HashMap<String, String>,
+
+    /// Reverse projection: target URL -> the first [`Slug`] that was minted
+    /// for it, folded from `events`. Lets the random-slug path dedup in
+    /// O(1).
+    by_url: HashMap<String, Slug>,
+
+    /// Projection: slug -> redirect count, folded from `events`.
     stats: BTreeMap<String, u64>,
+
+    /// Sequence number for the next `ShortLinkCreated` event appended to the
+    /// log.
+    next_seq: u64,
+
+    /// Length, alphabet and retry budget for minting random slugs.
+    slug_config: SlugConfig,
+
+    /// Host this service's short links are served from, e.g. `short.example`.
+    /// `is_shortened` only recognizes a full URL as one of ours when its
+    /// host matches; with no base domain configured, it only recognizes
+    /// bare slugs.
+    base_domain: Option<String>,
+}
+
+impl Default for UrlShortenerService {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl UrlShortenerService {
     /// Creates a new instance of the service
     pub fn new() -> Self {
+        Self::with_config(SlugConfig::default())
+    }
+
+    /// Creates a new instance of the service, minting random slugs according
+    /// to `config` instead of the default 7-character alphanumeric scheme.
+    pub fn with_config(config: SlugConfig) -> Self {
         Self {
+            events: Vec::new(),
             slugs: HashMap::new(),
+            by_url: HashMap::new(),
             stats: BTreeMap::new(),
+            next_seq: 0,
+            slug_config: config.sanitized(),
+            base_domain: None,
+        }
+    }
+
+    /// Tells the service which host its short links are served from, so
+    /// [`ResolveHandler::is_shortened`](queries::ResolveHandler::is_shortened)
+    /// can recognize full short URLs in addition to bare slugs.
+    pub fn with_base_domain(mut self, domain: impl Into<String>) -> Self {
+        self.base_domain = Some(domain.into());
+        self
+    }
+
+    /// Rebuilds a service purely from a previously recorded event stream,
+    /// minting future random slugs with [`SlugConfig::default`].
+    ///
+    /// Folding happens in order, so slug uniqueness and redirect counts come
+    /// out identical to the live-mutation path. If the service was running
+    /// with a non-default [`SlugConfig`] before the restart, use
+    /// [`UrlShortenerService::replay_with_config`] instead so that
+    /// configuration isn't silently lost.
+    pub fn replay(events: Vec<Event>) -> Self {
+        Self::replay_with_config(events, SlugConfig::default())
+    }
+
+    /// Rebuilds a service from a previously recorded event stream, minting
+    /// future random slugs according to `config` rather than reverting to
+    /// the default alphabet/length.
+    pub fn replay_with_config(events: Vec<Event>, config: SlugConfig) -> Self {
+        let mut service = Self::with_config(config);
+        for event in events {
+            if let Event::ShortLinkCreated { at_seq, .. } = &event {
+                service.next_seq = service.next_seq.max(at_seq + 1);
+            }
+            service.apply(&event);
+            service.events.push(event);
+        }
+        service
+    }
+
+    /// Folds a single [`Event`] into the in-memory projections. This is the
+    /// only place allowed to mutate `slugs`/`stats`, so live handling and
+    /// replay can never drift apart.
+    fn apply(&mut self, event: &Event) {
+        match event {
+            Event::ShortLinkCreated { slug, url, .. } => {
+                self.slugs.insert(slug.0.clone(), url.0.clone());
+                self.by_url.entry(url.0.clone()).or_insert_with(|| slug.clone());
+                self.stats.entry(slug.0.clone()).or_insert(0);
+            }
+            Event::Redirected { slug } => {
+                if let Some(count) = self.stats.get_mut(&slug.0) {
+                    *count += 1;
+                }
+            }
+        }
+    }
+
+    /// Appends `event` to the log and folds it into the projections.
+    fn record(&mut self, event: Event) {
+        self.apply(&event);
+        self.events.push(event);
+    }
+
+    /// Mints a random slug per `self.slug_config`, retrying on collision
+    /// with an existing or reserved slug for up to `max_attempts` attempts
+    /// in total.
+    fn generate_unique_slug(&self) -> Result<Slug, ShortenerError> {
+        let mut rng = rand::thread_rng();
+        for _ in 0..self.slug_config.max_attempts {
+            let candidate: String = (0..self.slug_config.length)
+                .map(|_| {
+                    let idx = rng.gen_range(0..self.slug_config.alphabet.len());
+                    self.slug_config.alphabet[idx]
+                })
+                .collect();
+            if !self.slugs.contains_key(&candidate) && !is_reserved(&candidate) {
+                return Ok(Slug(candidate));
+            }
         }
+        Err(ShortenerError::SlugAlreadyInUse)
     }
 }
 
@@ -154,39 +406,48 @@ impl commands::CommandHandler for UrlShortenerService {
         url: Url,
         slug: Option<Slug>,
     ) -> Result<ShortLink, ShortenerError> {
-        let sl: Slug;
-        match slug {
-            Some(s) => sl = s,
+        let url = normalize_http_url(&url)?;
+
+        let sl = match slug {
+            Some(s) => {
+                if is_reserved(&s.0) {
+                    return Err(ShortenerError::SlugAlreadyInUse);
+                }
+                s
+            }
             None => {
-                let rnd7: String = rand::thread_rng()
-                    .sample_iter(&Alphanumeric)
-                    .take(7)
-                    .map(char::from)
-                    .collect();
-                sl = Slug(rnd7);
+                // Random-slug requests are idempotent: resubmitting the same
+                // URL returns the short link minted for it the first time
+                // instead of minting a fresh random slug.
+                if let Some(existing) = self.by_url.get(&url.0) {
+                    return Ok(ShortLink {
+                        url,
+                        slug: existing.clone(),
+                    });
+                }
+                self.generate_unique_slug()?
             }
         };
-        match Vurl::parse(&url.0) {
-            Ok(_) => match self.slugs.get(&sl.0) {
-                Some(_) => Err(ShortenerError::SlugAlreadyInUse),
-                None => {
-                    self.slugs.insert(sl.0.clone(), url.0.clone());
-                    Ok(ShortLink { url, slug: sl })
-                }
-            },
-            Err(_) => Err(ShortenerError::InvalidUrl),
+
+        if self.slugs.contains_key(&sl.0) {
+            return Err(ShortenerError::SlugAlreadyInUse);
         }
+        let at_seq = self.next_seq;
+        self.next_seq += 1;
+        self.record(Event::ShortLinkCreated {
+            slug: sl.clone(),
+            url: url.clone(),
+            at_seq,
+        });
+        Ok(ShortLink { url, slug: sl })
     }
 
     fn handle_redirect(&mut self, slug: Slug) -> Result<ShortLink, ShortenerError> {
         match self.slugs.get(&slug.0) {
             Some(u) => {
-                let inc = self.stats.entry(slug.0.clone()).or_insert(0);
-                *inc += 1;
-                Ok(ShortLink {
-                    url: Url(u.clone()),
-                    slug,
-                })
+                let url = Url(u.clone());
+                self.record(Event::Redirected { slug: slug.clone() });
+                Ok(ShortLink { url, slug })
             }
             None => Err(ShortenerError::SlugNotFound),
         }
@@ -195,24 +456,76 @@ impl commands::CommandHandler for UrlShortenerService {
 
 impl queries::QueryHandler for UrlShortenerService {
     fn get_stats(&self, slug: Slug) -> Result<Stats, ShortenerError> {
-        todo!()
-        // match self.stats.get(&slug.0) {
-        //     Some(&redirects) => Ok(Stats {
-        //         link: Url(u.clone()),
-        //         redirects,
-        //     }),
-        //     None => Err(ShortenerError::SlugNotFound),
-        // }
+        match (self.slugs.get(&slug.0), self.stats.get(&slug.0)) {
+            (Some(u), Some(&redirects)) => Ok(Stats {
+                link: ShortLink {
+                    url: Url(u.clone()),
+                    slug,
+                },
+                redirects,
+            }),
+            _ => Err(ShortenerError::SlugNotFound),
+        }
     }
 }
 
+impl queries::ResolveHandler for UrlShortenerService {
+    fn resolve(&self, slug: Slug) -> Result<Url, ShortenerError> {
+        self.slugs
+            .get(&slug.0)
+            .map(|u| Url(u.clone()))
+            .ok_or(ShortenerError::SlugNotFound)
+    }
+
+    fn is_shortened(&self, url: &Url) -> bool {
+        // A bare slug, e.g. "my-awesome-slug".
+        if self.slugs.contains_key(&url.0) {
+            return true;
+        }
+
+        // A full short URL only counts if its host is the configured base
+        // domain; otherwise any unrelated long URL whose last path segment
+        // happens to match one of our slugs would be misreported as ours.
+        let Some(base_domain) = &self.base_domain else {
+            return false;
+        };
+        match Vurl::parse(&url.0) {
+            Ok(parsed) if parsed.host_str() == Some(base_domain.as_str()) => {
+                self.slugs.contains_key(slug_candidate(&url.0))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Extracts the trailing path segment of a possible short URL, which is
+/// where [`UrlShortenerService`] would have placed the [`Slug`].
+fn slug_candidate(s: &str) -> &str {
+    s.rsplit('/').next().unwrap_or(s)
+}
+
+/// Validates that `url` is an `http`/`https` URL with a non-empty host, and
+/// returns it normalized (lowercase scheme/host, default ports stripped) so
+/// equivalent URLs dedup to the same entry in `by_url`.
+fn normalize_http_url(url: &Url) -> Result<Url, ShortenerError> {
+    let parsed = Vurl::parse(&url.0).map_err(|_| ShortenerError::InvalidUrl)?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(ShortenerError::InvalidUrl);
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(ShortenerError::InvalidUrl);
+    }
+    Ok(Url(parsed.into()))
+}
+
 // Dummy fun
 fn main() {}
 
 #[cfg(test)]
 mod tests {
-    use super::{ShortLink, Slug, Url};
+    use super::{ShortLink, Slug, SlugConfig, Url};
     use crate::commands::CommandHandler;
+    use crate::queries::{QueryHandler, ResolveHandler};
     use crate::UrlShortenerService;
 
     #[test]
@@ -291,4 +604,234 @@ mod tests {
             shortener.handle_redirect(slug).expect("not implemented")
         );
     }
+
+    #[test]
+    fn replay_reconstructs_identical_state() {
+        let my_url = Url("https://www.example.com/".to_string());
+        let my_slug = Slug("my-awesome-slug".to_string());
+        let mut shortener = UrlShortenerService::new();
+
+        shortener
+            .handle_create_short_link(my_url.clone(), Some(my_slug.clone()))
+            .expect("not implemented");
+        shortener
+            .handle_redirect(my_slug.clone())
+            .expect("not implemented");
+        shortener
+            .handle_redirect(my_slug.clone())
+            .expect("not implemented");
+
+        let replayed = UrlShortenerService::replay(shortener.events.clone());
+
+        assert_eq!(
+            shortener.get_stats(my_slug.clone()).unwrap(),
+            replayed.get_stats(my_slug).unwrap()
+        );
+    }
+
+    #[test]
+    fn replay_with_config_preserves_non_default_slug_config() {
+        let mut shortener = UrlShortenerService::with_config(SlugConfig::unambiguous(10));
+        shortener
+            .handle_create_short_link(Url("https://www.example.com/".to_string()), None)
+            .expect("not implemented");
+
+        let mut replayed = UrlShortenerService::replay_with_config(
+            shortener.events.clone(),
+            SlugConfig::unambiguous(10),
+        );
+        let link = replayed
+            .handle_create_short_link(Url("https://www.other.example/".to_string()), None)
+            .expect("not implemented");
+
+        assert_eq!(link.slug.0.len(), 10);
+        assert!(link
+            .slug
+            .0
+            .chars()
+            .all(|c| !['0', 'O', '1', 'l'].contains(&c)));
+    }
+
+    #[test]
+    fn resubmitting_same_url_returns_existing_random_slug() {
+        let my_url = Url("https://www.example.com/".to_string());
+        let mut shortener = UrlShortenerService::new();
+
+        let first = shortener
+            .handle_create_short_link(my_url.clone(), None)
+            .expect("not implemented");
+        let second = shortener
+            .handle_create_short_link(my_url, None)
+            .expect("not implemented");
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn custom_slug_for_known_url_still_creates_an_alias() {
+        let my_url = Url("https://www.example.com/".to_string());
+        let alias = Slug("alias".to_string());
+        let mut shortener = UrlShortenerService::new();
+
+        let first = shortener
+            .handle_create_short_link(my_url.clone(), None)
+            .expect("not implemented");
+        let aliased = shortener
+            .handle_create_short_link(my_url, Some(alias.clone()))
+            .expect("not implemented");
+
+        assert_ne!(first.slug, aliased.slug);
+        assert_eq!(aliased.slug, alias);
+    }
+
+    #[test]
+    fn resolve_previews_without_counting_a_redirect() {
+        let my_url = Url("https://www.example.com/".to_string());
+        let my_slug = Slug("my-awesome-slug".to_string());
+        let mut shortener = UrlShortenerService::new();
+
+        shortener
+            .handle_create_short_link(my_url.clone(), Some(my_slug.clone()))
+            .expect("not implemented");
+
+        assert_eq!(shortener.resolve(my_slug.clone()).unwrap(), my_url);
+        assert_eq!(shortener.get_stats(my_slug).unwrap().redirects, 0);
+    }
+
+    #[test]
+    fn is_shortened_recognizes_bare_slugs_and_full_urls() {
+        let my_url = Url("https://www.example.com/".to_string());
+        let my_slug = Slug("my-awesome-slug".to_string());
+        let mut shortener = UrlShortenerService::new().with_base_domain("short.example");
+
+        shortener
+            .handle_create_short_link(my_url, Some(my_slug))
+            .expect("not implemented");
+
+        assert!(shortener.is_shortened(&Url("my-awesome-slug".to_string())));
+        assert!(shortener.is_shortened(&Url("https://short.example/my-awesome-slug".to_string())));
+        assert!(!shortener.is_shortened(&Url("https://short.example/unknown".to_string())));
+    }
+
+    #[test]
+    fn is_shortened_ignores_path_segment_matches_on_unrelated_hosts() {
+        let my_url = Url("https://internal.example.com/data".to_string());
+        let my_slug = Slug("reports".to_string());
+
+        // No base domain configured: a full URL must never be recognized by
+        // path segment alone, regardless of host.
+        let mut shortener = UrlShortenerService::new();
+        shortener
+            .handle_create_short_link(my_url.clone(), Some(my_slug.clone()))
+            .expect("not implemented");
+        assert!(!shortener.is_shortened(&Url(
+            "https://totally-unrelated-site.com/monthly/reports".to_string()
+        )));
+
+        // Base domain configured: a matching path segment on a different
+        // host still must not count as one of ours.
+        let mut shortener = UrlShortenerService::new().with_base_domain("short.example");
+        shortener
+            .handle_create_short_link(my_url, Some(my_slug))
+            .expect("not implemented");
+        assert!(!shortener.is_shortened(&Url(
+            "https://totally-unrelated-site.com/monthly/reports".to_string()
+        )));
+    }
+
+    #[test]
+    fn rejects_non_http_schemes() {
+        let mut shortener = UrlShortenerService::new();
+
+        assert_eq!(
+            Err(crate::ShortenerError::InvalidUrl),
+            shortener.handle_create_short_link(
+                Url("mailto:someone@example.com".to_string()),
+                None
+            )
+        );
+        assert_eq!(
+            Err(crate::ShortenerError::InvalidUrl),
+            shortener.handle_create_short_link(Url("javascript:alert(1)".to_string()), None)
+        );
+    }
+
+    #[test]
+    fn normalizes_scheme_host_and_default_port_for_dedup() {
+        let mut shortener = UrlShortenerService::new();
+
+        let first = shortener
+            .handle_create_short_link(Url("HTTP://Example.com:80/".to_string()), None)
+            .expect("not implemented");
+        let second = shortener
+            .handle_create_short_link(Url("http://example.com/".to_string()), None)
+            .expect("not implemented");
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn rejects_reserved_custom_slug() {
+        let mut shortener = UrlShortenerService::new();
+
+        assert_eq!(
+            Err(crate::ShortenerError::SlugAlreadyInUse),
+            shortener.handle_create_short_link(
+                Url("https://www.example.com/".to_string()),
+                Some(Slug("api".to_string()))
+            )
+        );
+    }
+
+    #[test]
+    fn random_slug_respects_configured_length_and_alphabet() {
+        let mut shortener = UrlShortenerService::with_config(SlugConfig::unambiguous(10));
+
+        let link = shortener
+            .handle_create_short_link(Url("https://www.example.com/".to_string()), None)
+            .expect("not implemented");
+
+        assert_eq!(link.slug.0.len(), 10);
+        assert!(link
+            .slug
+            .0
+            .chars()
+            .all(|c| !['0', 'O', '1', 'l'].contains(&c)));
+    }
+
+    #[test]
+    fn random_slug_generation_retries_on_collision() {
+        // One-character slugs over a two-letter alphabet force collisions almost
+        // immediately, exercising the bounded retry loop.
+        let mut config = SlugConfig::new(1);
+        config.alphabet = vec!['a', 'b'];
+        config.max_attempts = 100;
+        let mut shortener = UrlShortenerService::with_config(config);
+
+        let mut minted = std::collections::HashSet::new();
+        for i in 0..2 {
+            let link = shortener
+                .handle_create_short_link(
+                    Url(format!("https://www.example.com/{i}")),
+                    None,
+                )
+                .expect("not implemented");
+            minted.insert(link.slug.0);
+        }
+
+        assert_eq!(minted.len(), 2);
+    }
+
+    #[test]
+    fn with_config_falls_back_on_empty_alphabet_or_zero_length() {
+        let mut config = SlugConfig::new(0);
+        config.alphabet = vec![];
+        let mut shortener = UrlShortenerService::with_config(config);
+
+        let link = shortener
+            .handle_create_short_link(Url("https://www.example.com/".to_string()), None)
+            .expect("not implemented");
+
+        assert_eq!(link.slug.0.len(), SlugConfig::default().length);
+    }
 }